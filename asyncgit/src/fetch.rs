@@ -1,26 +1,55 @@
 use crate::{
 	error::{Error, Result},
 	sync::{
-		cred::BasicAuthCredential,
-		remotes::{fetch, push::ProgressNotification},
+		cred::{BasicAuthCredential, Credential, SshCredential},
+		remotes::{fetch, get_remotes, push::ProgressNotification, AuthCache},
 	},
 	AsyncGitNotification, RemoteProgress, CWD,
 };
 use crossbeam_channel::{unbounded, Sender};
 use std::{
-	sync::{Arc, Mutex},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
 	thread,
+	time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+/// periodic fetch backs off its interval by doubling it, per consecutive
+/// failure, up to this factor
+const MAX_BACKOFF_MULTIPLIER: u32 = 8;
+
+/// how long the periodic fetch sleeps between polls for the tick it
+/// kicked off to finish
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 ///
 #[derive(Default, Clone, Debug)]
 pub struct FetchRequest {
-	///
-	pub remote: String,
+	/// remote to fetch from, or `None` to fetch every configured remote
+	pub remote: Option<String>,
 	///
 	pub branch: String,
 	///
 	pub basic_credential: Option<BasicAuthCredential>,
+	///
+	pub ssh_credential: Option<SshCredential>,
+	/// remove local remote-tracking branches that no longer exist upstream
+	pub prune: bool,
+}
+
+impl FetchRequest {
+	/// the credential to present to the remote, preferring an explicit
+	/// SSH key over a basic auth credential when both are set
+	fn credential(&self) -> Option<Credential> {
+		self.ssh_credential
+			.clone()
+			.map(Credential::Ssh)
+			.or_else(|| {
+				self.basic_credential.clone().map(Credential::Basic)
+			})
+	}
 }
 
 #[derive(Default, Clone, Debug)]
@@ -28,11 +57,34 @@ struct FetchState {
 	request: FetchRequest,
 }
 
+/// outcome of a finished fetch
+#[derive(Clone, Debug)]
+pub enum FetchResult {
+	/// fetch completed; `bytes` is the total transferred across every
+	/// remote that was fetched and `errors` carries a `(remote, message)`
+	/// pair for each remote that failed
+	Done {
+		///
+		bytes: usize,
+		///
+		errors: Vec<(String, String)>,
+	},
+	/// fetch failed outright before any remote could be contacted
+	Error(String),
+	/// fetch was aborted via [`AsyncFetch::cancel`]
+	Cancelled,
+}
+
 ///
 pub struct AsyncFetch {
 	state: Arc<Mutex<Option<FetchState>>>,
-	last_result: Arc<Mutex<Option<(usize, String)>>>,
+	last_result: Arc<Mutex<Option<FetchResult>>>,
 	progress: Arc<Mutex<Option<ProgressNotification>>>,
+	auth_cache: Arc<Mutex<AuthCache>>,
+	cancellation: Arc<AtomicBool>,
+	/// remote currently being fetched, for namespacing progress when
+	/// [`FetchRequest::remote`] is `None`
+	current_remote: Arc<Mutex<Option<String>>>,
 	sender: Sender<AsyncGitNotification>,
 }
 
@@ -43,6 +95,9 @@ impl AsyncFetch {
 			state: Arc::new(Mutex::new(None)),
 			last_result: Arc::new(Mutex::new(None)),
 			progress: Arc::new(Mutex::new(None)),
+			auth_cache: Arc::new(Mutex::new(AuthCache::new())),
+			cancellation: Arc::new(AtomicBool::new(false)),
+			current_remote: Arc::new(Mutex::new(None)),
 			sender: sender.clone(),
 		}
 	}
@@ -53,8 +108,18 @@ impl AsyncFetch {
 		Ok(state.is_some())
 	}
 
+	/// aborts the in-flight fetch, if any; its result, as returned by
+	/// [`Self::last_result`], is then [`FetchResult::Cancelled`]
+	pub fn cancel(&self) -> Result<()> {
+		if self.is_pending()? {
+			self.cancellation.store(true, Ordering::Relaxed);
+		}
+
+		Ok(())
+	}
+
 	///
-	pub fn last_result(&self) -> Result<Option<(usize, String)>> {
+	pub fn last_result(&self) -> Result<Option<FetchResult>> {
 		let res = self.last_result.lock()?;
 		Ok(res.clone())
 	}
@@ -65,9 +130,21 @@ impl AsyncFetch {
 		Ok(res.as_ref().map(|progress| progress.clone().into()))
 	}
 
+	/// the remote currently being fetched, only meaningful while
+	/// [`Self::is_pending`] and a [`FetchRequest`] with `remote: None`
+	/// is working through every configured remote
+	pub fn remote(&self) -> Result<Option<String>> {
+		let res = self.current_remote.lock()?;
+		Ok(res.clone())
+	}
+
 	///
 	pub fn request(&mut self, params: FetchRequest) -> Result<()> {
-		log::trace!("request: {}/{}", params.remote, params.branch);
+		log::trace!(
+			"request: {}/{}",
+			params.remote.as_deref().unwrap_or("*"),
+			params.branch
+		);
 
 		if self.is_pending()? {
 			log::trace!("request ignored, still pending");
@@ -76,10 +153,15 @@ impl AsyncFetch {
 
 		self.set_request(&params)?;
 		RemoteProgress::set_progress(&self.progress, None)?;
+		self.cancellation.store(false, Ordering::Relaxed);
+		*self.current_remote.lock()? = None;
 
 		let arc_state = Arc::clone(&self.state);
 		let arc_res = Arc::clone(&self.last_result);
 		let arc_progress = Arc::clone(&self.progress);
+		let arc_auth_cache = Arc::clone(&self.auth_cache);
+		let arc_cancellation = Arc::clone(&self.cancellation);
+		let arc_current_remote = Arc::clone(&self.current_remote);
 		let sender = self.sender.clone();
 
 		thread::spawn(move || {
@@ -89,6 +171,9 @@ impl AsyncFetch {
 				arc_progress,
 				&arc_res,
 				&arc_state,
+				&arc_auth_cache,
+				&arc_cancellation,
+				&arc_current_remote,
 			);
 
 			if let Err(e) = res {
@@ -130,18 +215,22 @@ impl AsyncFetch {
 	}
 
 	fn set_result(
-		arc_result: &Arc<Mutex<Option<(usize, String)>>>,
-		res: Result<usize>,
+		arc_result: &Arc<Mutex<Option<FetchResult>>>,
+		bytes: usize,
+		errors: Vec<(String, String)>,
+		cancellation: &Arc<AtomicBool>,
 	) -> Result<()> {
 		let mut last_res = arc_result.lock()?;
 
-		*last_res = match res {
-			Ok(bytes) => Some((bytes, String::new())),
-			Err(e) => {
-				log::error!("fetch error: {}", e);
-				Some((0, e.to_string()))
-			}
-		};
+		for (remote, e) in &errors {
+			log::error!("fetch error ({}): {}", remote, e);
+		}
+
+		*last_res = Some(if cancellation.load(Ordering::Relaxed) {
+			FetchResult::Cancelled
+		} else {
+			FetchResult::Done { bytes, errors }
+		});
 
 		Ok(())
 	}
@@ -150,8 +239,11 @@ impl AsyncFetch {
 		params: FetchRequest,
 		sender: &Sender<AsyncGitNotification>,
 		arc_progress: Arc<Mutex<Option<ProgressNotification>>>,
-		arc_res: &Arc<Mutex<Option<(usize, String)>>>,
+		arc_res: &Arc<Mutex<Option<FetchResult>>>,
 		arc_state: &Arc<Mutex<Option<FetchState>>>,
+		arc_auth_cache: &Arc<Mutex<AuthCache>>,
+		arc_cancellation: &Arc<AtomicBool>,
+		arc_current_remote: &Arc<Mutex<Option<String>>>,
 	) -> Result<()> {
 		let (progress_sender, receiver) = unbounded();
 
@@ -162,20 +254,354 @@ impl AsyncFetch {
 			arc_progress,
 		);
 
-		let res = fetch(
-			CWD,
-			&params.branch,
-			params.basic_credential,
-			Some(progress_sender.clone()),
-		);
+		// fetching every remote doesn't assume each one tracks a
+		// branch named `params.branch` - it fetches whatever refspecs
+		// that remote is configured with instead
+		let (remotes, branch) = match &params.remote {
+			Some(remote) => (vec![remote.clone()], params.branch.as_str()),
+			None => match get_remotes(CWD) {
+				Ok(remotes) => (remotes, ""),
+				Err(e) => {
+					*arc_res.lock()? =
+						Some(FetchResult::Error(e.to_string()));
+					return Err(e);
+				}
+			},
+		};
+
+		let mut bytes = 0;
+		let mut errors = Vec::new();
+
+		for remote in remotes {
+			if arc_cancellation.load(Ordering::Relaxed) {
+				break;
+			}
+
+			*arc_current_remote.lock()? = Some(remote.clone());
+
+			match fetch(
+				CWD,
+				&remote,
+				branch,
+				params.credential(),
+				Some(progress_sender.clone()),
+				Arc::clone(arc_auth_cache),
+				sender.clone(),
+				Arc::clone(arc_cancellation),
+				params.prune,
+			) {
+				Ok(transferred) => bytes += transferred,
+				Err(e) => errors.push((remote, e.to_string())),
+			}
+
+			if arc_cancellation.load(Ordering::Relaxed) {
+				break;
+			}
+		}
 
 		progress_sender.send(ProgressNotification::Done)?;
 
 		handle.join()?;
 
-		Self::set_result(arc_res, res)?;
+		Self::set_result(arc_res, bytes, errors, arc_cancellation)?;
 		Self::clear_request(arc_state)?;
 
 		Ok(())
 	}
 }
+
+/// adds up to +/-10% jitter to `interval` so that many repos fetching on
+/// the same configured interval don't all hit the network in lockstep
+fn jitter(interval: Duration) -> Duration {
+	let nanos = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.subsec_nanos());
+
+	let ratio = (f64::from(nanos % 1000) / 1000.0).mul_add(0.2, -0.1);
+
+	interval.mul_f64(1.0 + ratio)
+}
+
+/// doubles `interval` per consecutive failure, capped at
+/// [`MAX_BACKOFF_MULTIPLIER`], so a misconfigured remote is retried less
+/// and less often instead of hammering the network
+fn backoff(interval: Duration, consecutive_failures: u32) -> Duration {
+	let multiplier =
+		1_u32.saturating_shl(consecutive_failures.min(u32::BITS - 1));
+
+	interval.saturating_mul(multiplier.min(MAX_BACKOFF_MULTIPLIER))
+}
+
+/// background subsystem built on top of [`AsyncFetch`] that re-issues a
+/// [`FetchRequest`] on a configurable interval, quietly keeping
+/// remote-tracking branches in sync without the user manually fetching
+pub struct AsyncPeriodicFetch {
+	running: Arc<AtomicBool>,
+	handle: Option<thread::JoinHandle<()>>,
+	sender: Sender<AsyncGitNotification>,
+	/// shared with the poller thread so [`Self::stop`] can abort a tick
+	/// that's already in flight instead of orphaning it
+	fetch: Arc<Mutex<AsyncFetch>>,
+}
+
+impl AsyncPeriodicFetch {
+	///
+	pub fn new(sender: &Sender<AsyncGitNotification>) -> Self {
+		Self {
+			running: Arc::new(AtomicBool::new(false)),
+			handle: None,
+			sender: sender.clone(),
+			fetch: Arc::new(Mutex::new(AsyncFetch::new(sender))),
+		}
+	}
+
+	/// starts the background poller, stopping a previously started one
+	/// first; `interval` is the base delay between ticks before jitter
+	/// and backoff are applied
+	pub fn start(
+		&mut self,
+		interval: Duration,
+		request: FetchRequest,
+	) -> Result<()> {
+		self.stop()?;
+
+		self.running.store(true, Ordering::Relaxed);
+
+		let running = Arc::clone(&self.running);
+		let sender = self.sender.clone();
+		let fetch = Arc::clone(&self.fetch);
+
+		self.handle = Some(thread::spawn(move || {
+			Self::run(&running, &sender, interval, &request, &fetch);
+		}));
+
+		Ok(())
+	}
+
+	/// stops the poller: cancels a tick already in flight so it actually
+	/// aborts its network transfer instead of running to completion in
+	/// the background, then waits for the poller thread to notice and
+	/// exit
+	pub fn stop(&mut self) -> Result<()> {
+		self.running.store(false, Ordering::Relaxed);
+		self.fetch.lock()?.cancel()?;
+
+		if let Some(handle) = self.handle.take() {
+			handle.join().map_err(|_| {
+				Error::Generic(
+					"periodic fetch thread panicked".into(),
+				)
+			})?;
+		}
+
+		Ok(())
+	}
+
+	/// sleeps for `duration` in [`POLL_INTERVAL`] increments, checking
+	/// `running` between each one; returns `false` as soon as `running`
+	/// goes false so [`Self::stop`] never blocks for longer than one
+	/// increment instead of the whole (possibly backed-off) interval
+	fn sleep_interruptible(
+		running: &Arc<AtomicBool>,
+		duration: Duration,
+	) -> bool {
+		let mut remaining = duration;
+
+		while remaining > Duration::ZERO {
+			if !running.load(Ordering::Relaxed) {
+				return false;
+			}
+
+			let step = remaining.min(POLL_INTERVAL);
+			thread::sleep(step);
+			remaining -= step;
+		}
+
+		running.load(Ordering::Relaxed)
+	}
+
+	fn run(
+		running: &Arc<AtomicBool>,
+		sender: &Sender<AsyncGitNotification>,
+		interval: Duration,
+		request: &FetchRequest,
+		fetch: &Arc<Mutex<AsyncFetch>>,
+	) {
+		let mut consecutive_failures = 0;
+
+		while running.load(Ordering::Relaxed) {
+			if !Self::sleep_interruptible(
+				running,
+				jitter(backoff(interval, consecutive_failures)),
+			) {
+				break;
+			}
+
+			let is_pending = match fetch.lock() {
+				Ok(fetch) => fetch.is_pending().unwrap_or(true),
+				Err(_) => true,
+			};
+
+			if is_pending {
+				log::trace!(
+					"periodic fetch: previous fetch still pending, skipping tick"
+				);
+				continue;
+			}
+
+			let requested = match fetch.lock() {
+				Ok(mut fetch) => fetch.request(request.clone()),
+				Err(_) => Err(Error::Generic(
+					"periodic fetch lock poisoned".into(),
+				)),
+			};
+
+			if requested.is_err() {
+				consecutive_failures += 1;
+				continue;
+			}
+
+			// `stop()` may have flipped `running` to false and tried to
+			// cancel in the gap between the pending check above and the
+			// `request()` call, missing this fetch because it wasn't
+			// pending yet - check again here so it doesn't run on
+			// uncancelled. Wait unconditionally on `is_pending` (not
+			// `running`) so this loop, and therefore `stop()`'s
+			// `handle.join()`, doesn't return until the cancelled fetch
+			// has actually stopped.
+			if !running.load(Ordering::Relaxed) {
+				if let Ok(fetch) = fetch.lock() {
+					let _ = fetch.cancel();
+				}
+			}
+
+			while fetch
+				.lock()
+				.is_ok_and(|fetch| fetch.is_pending().unwrap_or(false))
+			{
+				thread::sleep(POLL_INTERVAL);
+			}
+
+			let last_result = fetch
+				.lock()
+				.ok()
+				.and_then(|fetch| fetch.last_result().ok())
+				.flatten();
+
+			match last_result {
+				Some(FetchResult::Done { bytes, errors })
+					if errors.is_empty() =>
+				{
+					consecutive_failures = 0;
+
+					if bytes > 0 {
+						sender
+							.send(AsyncGitNotification::Fetch)
+							.expect("send error");
+					}
+				}
+				Some(FetchResult::Done { .. } | FetchResult::Error(_)) => {
+					consecutive_failures += 1;
+				}
+				_ => {}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn credential_prefers_ssh_over_basic_when_both_set() {
+		let request = FetchRequest {
+			basic_credential: Some(BasicAuthCredential::new(
+				Some("user".into()),
+				Some("pass".into()),
+			)),
+			ssh_credential: Some(SshCredential {
+				use_ssh_agent: true,
+				..SshCredential::default()
+			}),
+			..FetchRequest::default()
+		};
+
+		assert!(matches!(
+			request.credential(),
+			Some(Credential::Ssh(_))
+		));
+	}
+
+	#[test]
+	fn credential_falls_back_to_basic_when_no_ssh_credential() {
+		let request = FetchRequest {
+			basic_credential: Some(BasicAuthCredential::new(
+				Some("user".into()),
+				Some("pass".into()),
+			)),
+			..FetchRequest::default()
+		};
+
+		assert!(matches!(
+			request.credential(),
+			Some(Credential::Basic(_))
+		));
+	}
+
+	#[test]
+	fn credential_is_none_when_nothing_set() {
+		assert!(FetchRequest::default().credential().is_none());
+	}
+
+	#[test]
+	fn backoff_is_unchanged_with_no_failures() {
+		let interval = Duration::from_secs(60);
+		assert_eq!(backoff(interval, 0), interval);
+	}
+
+	#[test]
+	fn backoff_doubles_per_consecutive_failure_up_to_the_cap() {
+		let interval = Duration::from_secs(60);
+
+		assert_eq!(backoff(interval, 1), interval * 2);
+		assert_eq!(backoff(interval, 2), interval * 4);
+		assert_eq!(
+			backoff(interval, 3),
+			interval * MAX_BACKOFF_MULTIPLIER
+		);
+		assert_eq!(
+			backoff(interval, 10),
+			interval * MAX_BACKOFF_MULTIPLIER
+		);
+	}
+
+	#[test]
+	fn jitter_stays_within_ten_percent_of_the_interval() {
+		let interval = Duration::from_secs(100);
+		let jittered = jitter(interval);
+
+		assert!(jittered >= Duration::from_secs(90));
+		assert!(jittered <= Duration::from_secs(110));
+	}
+
+	#[test]
+	fn sleep_interruptible_returns_false_once_stopped() {
+		let running = Arc::new(AtomicBool::new(false));
+
+		assert!(!AsyncPeriodicFetch::sleep_interruptible(
+			&running,
+			Duration::from_secs(60)
+		));
+	}
+
+	#[test]
+	fn sleep_interruptible_sleeps_the_full_duration_then_returns_true() {
+		let running = Arc::new(AtomicBool::new(true));
+
+		assert!(AsyncPeriodicFetch::sleep_interruptible(
+			&running,
+			Duration::from_millis(1)
+		));
+	}
+}
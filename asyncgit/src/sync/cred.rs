@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+/// username/password pair offered to an HTTPS remote
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct BasicAuthCredential {
+	///
+	pub username: Option<String>,
+	///
+	pub password: Option<String>,
+}
+
+impl BasicAuthCredential {
+	///
+	pub const fn new(
+		username: Option<String>,
+		password: Option<String>,
+	) -> Self {
+		Self { username, password }
+	}
+}
+
+/// key material offered to an SSH remote
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct SshCredential {
+	/// falls back to `~/.ssh/id_rsa` when `None`
+	pub private_key: Option<PathBuf>,
+	/// falls back to `~/.ssh/id_rsa.pub` when `None`
+	pub public_key: Option<PathBuf>,
+	///
+	pub passphrase: Option<String>,
+	/// try `ssh-agent` before falling back to the key files above
+	pub use_ssh_agent: bool,
+}
+
+/// credential used to authenticate a single fetch or push
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Credential {
+	///
+	Basic(BasicAuthCredential),
+	///
+	Ssh(SshCredential),
+}
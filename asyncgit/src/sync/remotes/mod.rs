@@ -0,0 +1,410 @@
+pub mod push;
+
+use crate::{
+	error::{Error, Result},
+	sync::cred::{Credential, SshCredential},
+	AsyncGitNotification,
+};
+use crossbeam_channel::Sender;
+use git2::{
+	Cred, CredentialType, FetchOptions, FetchPrune, Remote,
+	RemoteCallbacks, Repository,
+};
+use push::ProgressNotification;
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+};
+
+/// order in which the `credentials` callback offers authentication
+/// methods to a remote
+const CREDENTIAL_METHODS: [CredentialMethod; 3] = [
+	CredentialMethod::SshAgent,
+	CredentialMethod::SshKey,
+	CredentialMethod::UserPassPlaintext,
+];
+
+/// one of the credential methods git2 may ask for via
+/// `RemoteCallbacks::credentials`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CredentialMethod {
+	///
+	SshAgent,
+	///
+	SshKey,
+	///
+	UserPassPlaintext,
+}
+
+#[derive(Default, Clone, Debug)]
+struct AuthAttempt {
+	last_credential: Option<Credential>,
+	method_index: usize,
+}
+
+/// remembers, per remote URL, which credential was last offered so the
+/// `credentials` callback can recognize a rejection and step to the next
+/// method instead of failing the whole fetch outright
+#[derive(Default, Clone, Debug)]
+pub struct AuthCache {
+	attempts: HashMap<String, AuthAttempt>,
+}
+
+impl AuthCache {
+	///
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// called with the credential about to be offered; returns `true`
+	/// when this is a retry of the exact credential that was offered
+	/// last for `remote`, and records `candidate` as the last one
+	fn was_just_rejected(
+		&mut self,
+		remote: &str,
+		candidate: &Credential,
+	) -> bool {
+		let attempt =
+			self.attempts.entry(remote.to_string()).or_default();
+
+		let rejected = attempt
+			.last_credential
+			.as_ref()
+			.is_some_and(|last| last == candidate);
+
+		attempt.last_credential = Some(candidate.clone());
+
+		rejected
+	}
+
+	/// advances the remote to the next allowed credential method,
+	/// returning `None` once every method in [`CREDENTIAL_METHODS`] has
+	/// been exhausted
+	fn next_method(&mut self, remote: &str) -> Option<CredentialMethod> {
+		let attempt =
+			self.attempts.entry(remote.to_string()).or_default();
+
+		if attempt.method_index >= CREDENTIAL_METHODS.len() {
+			return None;
+		}
+
+		let method = CREDENTIAL_METHODS[attempt.method_index];
+		attempt.method_index += 1;
+
+		Some(method)
+	}
+
+	/// clears `remote`'s retry state, so a fresh call to [`fetch`] starts
+	/// back at [`CREDENTIAL_METHODS`]'s first method instead of
+	/// continuing to burn through attempts left over from an earlier,
+	/// unrelated fetch of the same remote
+	fn reset(&mut self, remote: &str) {
+		self.attempts.remove(remote);
+	}
+}
+
+fn repo(repo_path: &str) -> Result<Repository> {
+	Ok(Repository::open(repo_path)?)
+}
+
+/// every remote configured for `repo_path`
+pub fn get_remotes(repo_path: &str) -> Result<Vec<String>> {
+	let repo = repo(repo_path)?;
+	let remotes = repo.remotes()?;
+
+	Ok(remotes.iter().flatten().map(String::from).collect())
+}
+
+fn default_ssh_key_paths() -> (PathBuf, PathBuf) {
+	let home = std::env::var_os("HOME")
+		.map(PathBuf::from)
+		.unwrap_or_default();
+
+	(home.join(".ssh/id_rsa"), home.join(".ssh/id_rsa.pub"))
+}
+
+/// builds the `credentials` callback handed to `RemoteCallbacks`: it
+/// offers `ssh-agent`, then an explicit SSH key pair, then a basic auth
+/// credential, consulting `auth_cache` so that a credential offered again
+/// after a rejection sends an [`AsyncGitNotification::Fetch`] on `notify`;
+/// no fresh credential is obtained, the callback keeps offering
+/// `credential` through the remaining methods
+fn credentials_callback<'a>(
+	remote: String,
+	credential: Option<Credential>,
+	auth_cache: Arc<Mutex<AuthCache>>,
+	notify: Sender<AsyncGitNotification>,
+) -> impl FnMut(
+	&str,
+	Option<&str>,
+	CredentialType,
+) -> std::result::Result<Cred, git2::Error>
+       + 'a {
+	move |_url, username_from_url, allowed_types| {
+		let username = username_from_url.unwrap_or("git");
+
+		if let Some(offered) = credential.clone() {
+			let rejected = auth_cache
+				.lock()
+				.map(|mut cache| {
+					cache.was_just_rejected(&remote, &offered)
+				})
+				.unwrap_or(false);
+
+			if rejected {
+				notify.send(AsyncGitNotification::Fetch).ok();
+			}
+		}
+
+		// a method that doesn't suit this transport (e.g. ssh methods
+		// against a plain HTTPS remote) is skipped in favor of the next
+		// one instead of failing the callback outright - git2 never
+		// calls this closure again once it returns an `Err`
+		loop {
+			let method = auth_cache
+				.lock()
+				.ok()
+				.and_then(|mut cache| cache.next_method(&remote))
+				.ok_or_else(|| {
+					git2::Error::from_str(
+						"exhausted all credential methods for remote",
+					)
+				})?;
+
+			match (method, &credential) {
+				// an explicit SSH credential only goes through the agent
+				// when it opts in via `use_ssh_agent`
+				(CredentialMethod::SshAgent, maybe_ssh)
+					if allowed_types.contains(CredentialType::SSH_KEY)
+						&& !matches!(
+							maybe_ssh,
+							Some(Credential::Ssh(ssh)) if !ssh.use_ssh_agent
+						) =>
+				{
+					return Cred::ssh_key_from_agent(username);
+				}
+				(CredentialMethod::SshKey, maybe_ssh)
+					if allowed_types.contains(CredentialType::SSH_KEY) =>
+				{
+					let (default_private, default_public) =
+						default_ssh_key_paths();
+
+					let ssh = match maybe_ssh {
+						Some(Credential::Ssh(ssh)) => ssh.clone(),
+						_ => SshCredential::default(),
+					};
+
+					let private_key =
+						ssh.private_key.unwrap_or(default_private);
+					let public_key =
+						ssh.public_key.unwrap_or(default_public);
+
+					return Cred::ssh_key(
+						username,
+						Some(public_key.as_path()),
+						private_key.as_path(),
+						ssh.passphrase.as_deref(),
+					);
+				}
+				(
+					CredentialMethod::UserPassPlaintext,
+					Some(Credential::Basic(basic)),
+				) if allowed_types
+					.contains(CredentialType::USER_PASS_PLAINTEXT) =>
+				{
+					return Cred::userpass_plaintext(
+						basic.username.as_deref().unwrap_or(username),
+						basic.password.as_deref().unwrap_or_default(),
+					);
+				}
+				_ => {}
+			}
+		}
+	}
+}
+
+/// fetches `branch` (or, when empty, every refspec configured for the
+/// remote) from `remote`, authenticating with `credential` and pruning
+/// stale remote-tracking branches when `prune` is set; returns the
+/// number of bytes received
+#[allow(clippy::too_many_arguments)]
+pub fn fetch(
+	repo_path: &str,
+	remote: &str,
+	branch: &str,
+	credential: Option<Credential>,
+	progress_sender: Option<Sender<ProgressNotification>>,
+	auth_cache: Arc<Mutex<AuthCache>>,
+	notify: Sender<AsyncGitNotification>,
+	cancellation: Arc<AtomicBool>,
+	prune: bool,
+) -> Result<usize> {
+	let repo = repo(repo_path)?;
+	let mut git_remote: Remote = repo.find_remote(remote)?;
+
+	// retry state is scoped to this single fetch, not the lifetime of
+	// `auth_cache`'s owner, so attempts don't carry over into unrelated
+	// later fetches of the same remote (e.g. every tick of
+	// `AsyncPeriodicFetch`, which reuses one `AuthCache` for as long as
+	// the process runs)
+	if let Ok(mut cache) = auth_cache.lock() {
+		cache.reset(remote);
+	}
+
+	let mut callbacks = RemoteCallbacks::new();
+
+	callbacks.credentials(credentials_callback(
+		remote.to_string(),
+		credential,
+		auth_cache,
+		notify,
+	));
+
+	let progress_remote = remote.to_string();
+	let progress_sender_for_callback = progress_sender.clone();
+
+	callbacks.transfer_progress(move |stats| {
+		if let Some(sender) = &progress_sender_for_callback {
+			sender
+				.send(ProgressNotification::Transfer {
+					remote: progress_remote.clone(),
+					received_objects: stats.received_objects(),
+					total_objects: stats.total_objects(),
+					received_bytes: stats.received_bytes(),
+				})
+				.ok();
+		}
+
+		// returning `false` aborts the in-flight transfer
+		!cancellation.load(Ordering::Relaxed)
+	});
+
+	let mut options = FetchOptions::new();
+	options.remote_callbacks(callbacks);
+	// `Unspecified` defers to the remote's own `fetch.prune`/`remote.*.prune`
+	// git config instead of forcibly disabling pruning when the caller
+	// didn't ask for it
+	options.prune(if prune {
+		FetchPrune::On
+	} else {
+		FetchPrune::Unspecified
+	});
+
+	let refspecs: &[&str] =
+		if branch.is_empty() { &[] } else { &[branch] };
+
+	git_remote.fetch(refspecs, Some(&mut options), None)?;
+
+	let stats = git_remote.stats();
+
+	Ok(stats.received_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sync::cred::BasicAuthCredential;
+
+	fn basic(password: &str) -> Credential {
+		Credential::Basic(BasicAuthCredential::new(
+			Some("user".into()),
+			Some(password.into()),
+		))
+	}
+
+	#[test]
+	fn next_method_steps_through_every_method_then_stops() {
+		let mut cache = AuthCache::new();
+
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshAgent)
+		);
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshKey)
+		);
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::UserPassPlaintext)
+		);
+		assert_eq!(cache.next_method("origin"), None);
+	}
+
+	#[test]
+	fn next_method_is_tracked_independently_per_remote() {
+		let mut cache = AuthCache::new();
+
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshAgent)
+		);
+		assert_eq!(
+			cache.next_method("upstream"),
+			Some(CredentialMethod::SshAgent)
+		);
+	}
+
+	#[test]
+	fn was_just_rejected_detects_a_repeated_credential() {
+		let mut cache = AuthCache::new();
+		let credential = basic("hunter2");
+
+		assert!(!cache.was_just_rejected("origin", &credential));
+		assert!(cache.was_just_rejected("origin", &credential));
+	}
+
+	#[test]
+	fn was_just_rejected_is_false_for_a_fresh_credential() {
+		let mut cache = AuthCache::new();
+
+		assert!(!cache.was_just_rejected("origin", &basic("first")));
+		assert!(!cache.was_just_rejected("origin", &basic("second")));
+	}
+
+	#[test]
+	fn reset_lets_a_remote_start_back_at_the_first_method() {
+		let mut cache = AuthCache::new();
+
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshAgent)
+		);
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshKey)
+		);
+
+		cache.reset("origin");
+
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshAgent)
+		);
+	}
+
+	#[test]
+	fn reset_does_not_affect_other_remotes() {
+		let mut cache = AuthCache::new();
+
+		assert_eq!(
+			cache.next_method("origin"),
+			Some(CredentialMethod::SshAgent)
+		);
+		assert_eq!(
+			cache.next_method("upstream"),
+			Some(CredentialMethod::SshAgent)
+		);
+
+		cache.reset("origin");
+
+		assert_eq!(
+			cache.next_method("upstream"),
+			Some(CredentialMethod::SshKey)
+		);
+	}
+}
@@ -0,0 +1,22 @@
+/// progress reported while talking to a remote, shared by fetch and push
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgressNotification {
+	/// objects counted, about to start transferring
+	Counting {
+		///
+		remote: String,
+	},
+	/// transfer under way
+	Transfer {
+		///
+		remote: String,
+		///
+		received_objects: usize,
+		///
+		total_objects: usize,
+		///
+		received_bytes: usize,
+	},
+	/// the whole operation (every remote) finished
+	Done,
+}